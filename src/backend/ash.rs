@@ -1,8 +1,12 @@
+use std::collections::VecDeque;
+use std::ffi::{CStr, CString};
 use std::sync::atomic::AtomicU64;
 
 use ash::vk;
 
-use crate::{NanoSecond, ScopeId};
+use crate::{NanoSecond, PipelineStats, ScopeId};
+
+const MAX_FRAMES_IN_FLIGHT: usize = 4;
 
 pub trait VulkanBuffer {
     fn mapped_slice(&self) -> &[u8];
@@ -14,6 +18,182 @@ pub trait VulkanBackend {
 
     fn create_query_result_buffer(&mut self, bytes: usize) -> Self::Buffer;
     fn timestamp_period(&self) -> f32;
+
+    /// Pipeline statistics to capture alongside timestamps, or `None` to
+    /// only time scopes. Requires the `pipelineStatisticsQuery` feature to
+    /// be enabled on the device; returning `Some` when it isn't will fail
+    /// query pool creation.
+    ///
+    /// `PIPELINE_STATISTICS` queries are region queries and Vulkan forbids
+    /// two of them being open at once in the same command buffer, so when
+    /// scopes nest, only the outermost scope of a nested group actually
+    /// records statistics; its descendants are timed but get no `stats`.
+    fn pipeline_statistics_flags(&self) -> Option<vk::QueryPipelineStatisticFlags> {
+        None
+    }
+
+    /// Loader for `VK_EXT_debug_utils`, if the instance extension was
+    /// enabled. When present, scopes and query pools are named through it so
+    /// they show up in RenderDoc/Nsight; when absent, naming is skipped.
+    fn debug_utils(&self) -> Option<ash::ext::debug_utils::Device> {
+        None
+    }
+}
+
+/// A null-terminated scope label for `VK_EXT_debug_utils`, built without a
+/// heap allocation for names that fit in a small stack buffer. Scope names
+/// are arbitrary user strings, so an interior NUL (which a `CStr` can't
+/// contain) is truncated rather than rejected.
+enum ScopeLabel {
+    Stack([u8; Self::STACK_LEN], usize),
+    Heap(CString),
+}
+
+impl ScopeLabel {
+    const STACK_LEN: usize = 64;
+
+    fn new(name: &str) -> Self {
+        let bytes = name.as_bytes();
+        let len = bytes
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(bytes.len())
+            .min(bytes.len());
+        let bytes = &bytes[..len];
+
+        if len < Self::STACK_LEN {
+            let mut buf = [0u8; Self::STACK_LEN];
+            buf[..len].copy_from_slice(bytes);
+            Self::Stack(buf, len)
+        } else {
+            Self::Heap(CString::new(bytes).expect("interior NULs were already stripped"))
+        }
+    }
+
+    fn as_c_str(&self) -> &CStr {
+        match self {
+            Self::Stack(buf, len) => {
+                CStr::from_bytes_with_nul(&buf[..=*len]).expect("NUL-terminated by construction")
+            }
+            Self::Heap(cstring) => cstring.as_c_str(),
+        }
+    }
+}
+
+/// Owns a ring of `MAX_FRAMES_IN_FLIGHT` [`VulkanProfilerFrame`]s so readback
+/// never has to stall the GPU: `end_frame` copies query results out without
+/// `WAIT`, and a frame's results are only consumed, from `waiting_frames`,
+/// once they're reported available by the driver; the frame then goes back
+/// to `frame_pool` for reuse.
+///
+/// `begin_frame` grows `frame_pool` rather than panicking if the GPU falls
+/// behind and every pooled frame is still waiting on its readback. Like the
+/// rest of this module, frames are never torn down (no `vkDestroyQueryPool`
+/// anywhere here), so a frame allocated during a stall is kept around for
+/// the life of the `VulkanProfiler` rather than freed once the stall clears.
+pub struct VulkanProfiler<Buffer: VulkanBuffer> {
+    current_frame: Option<VulkanProfilerFrame<Buffer>>,
+    waiting_frames: VecDeque<VulkanProfilerFrame<Buffer>>,
+    frame_pool: Vec<VulkanProfilerFrame<Buffer>>,
+}
+
+impl<Buffer: VulkanBuffer> VulkanProfiler<Buffer> {
+    pub fn new(device: &ash::Device, mut backend: impl VulkanBackend<Buffer = Buffer>) -> Self {
+        let frame_pool = (0..MAX_FRAMES_IN_FLIGHT)
+            .map(|_| VulkanProfilerFrame::new(device, &mut backend))
+            .collect();
+
+        Self {
+            current_frame: None,
+            waiting_frames: VecDeque::with_capacity(MAX_FRAMES_IN_FLIGHT),
+            frame_pool,
+        }
+    }
+
+    /// Call this before recording any profiling scopes in the frame
+    pub fn begin_frame(
+        &mut self,
+        device: &ash::Device,
+        cmd: vk::CommandBuffer,
+        mut backend: impl VulkanBackend<Buffer = Buffer>,
+    ) {
+        assert!(self.current_frame.is_none(), "begin_frame called twice");
+
+        self.try_get_results();
+
+        crate::profiler().begin_frame();
+
+        // If the GPU hasn't caught up and every pooled frame is still
+        // waiting on its readback, grow the pool instead of stalling or
+        // crashing the caller, same as `GlProfiler::begin_frame` does. This
+        // growth is permanent for the life of the profiler; see the type's
+        // doc comment.
+        let frame = self
+            .frame_pool
+            .pop()
+            .unwrap_or_else(|| VulkanProfilerFrame::new(device, &mut backend));
+        frame.reset(device, cmd);
+        self.current_frame = Some(frame);
+    }
+
+    /// Call this after recording all profiling scopes in the frame
+    pub fn end_frame(&mut self, device: &ash::Device, cmd: vk::CommandBuffer) {
+        let frame = self
+            .current_frame
+            .take()
+            .expect("end_frame called before begin_frame");
+
+        frame.copy_results(device, cmd);
+        self.waiting_frames.push_back(frame);
+
+        // Sanity check: a healthy pipeline drains this within a handful of
+        // frames. This is a last-resort stall detector, not a hard limit.
+        assert!(
+            self.waiting_frames.len() < 10,
+            "Vulkan queries failed to become available"
+        );
+
+        crate::profiler().end_frame();
+    }
+
+    pub fn begin_scope(
+        &self,
+        device: &ash::Device,
+        cb: vk::CommandBuffer,
+        scope_id: ScopeId,
+        name: &str,
+    ) -> VulkanActiveScope {
+        self.current_frame
+            .as_ref()
+            .expect("begin_scope called before begin_frame")
+            .begin_scope(device, cb, scope_id, name)
+    }
+
+    pub fn end_scope(&self, device: &ash::Device, cb: vk::CommandBuffer, active_scope: VulkanActiveScope) {
+        let scope_id = active_scope.scope_id;
+
+        self.current_frame
+            .as_ref()
+            .expect("end_scope called before begin_frame")
+            .end_scope(device, cb, active_scope);
+
+        crate::profiler().end_scope(scope_id);
+    }
+
+    fn try_get_results(&mut self) {
+        while let Some(frame) = self.waiting_frames.front() {
+            let Some((durations, stats)) = frame.try_read_results() else {
+                break;
+            };
+
+            crate::profiler().report_durations(durations.into_iter());
+            if let Some(stats) = stats {
+                crate::profiler().report_pipeline_stats(stats.into_iter());
+            }
+
+            self.frame_pool.push(self.waiting_frames.pop_front().unwrap());
+        }
+    }
 }
 
 pub struct VulkanProfilerFrame<Buffer: VulkanBuffer> {
@@ -22,17 +202,133 @@ pub struct VulkanProfilerFrame<Buffer: VulkanBuffer> {
     next_query_idx: std::sync::atomic::AtomicU32,
     query_scope_ids: Box<[AtomicU64]>,
     timestamp_period: f32,
+
+    stats: Option<StatsQueryPool<Buffer>>,
+    debug_utils: Option<ash::ext::debug_utils::Device>,
+}
+
+struct StatsQueryPool<Buffer: VulkanBuffer> {
+    buffer: Buffer,
+    query_pool: vk::QueryPool,
+    next_query_idx: std::sync::atomic::AtomicU32,
+    query_scope_ids: Box<[AtomicU64]>,
+    flags: vk::QueryPipelineStatisticFlags,
+    values_per_query: usize,
+    // Number of currently open scopes wanting a statistics query, including
+    // ones that didn't get one because they're nested; see `begin_scope`.
+    // `PIPELINE_STATISTICS` queries are region queries and the same command
+    // buffer can't have two of them open at once, so only the outermost
+    // stats-covered scope actually records one.
+    active_depth: std::sync::atomic::AtomicU32,
 }
 
 const MAX_QUERY_COUNT: usize = 1024;
-type DurationRange = [u64; 2];
 
+/// Raw `VK_QUERY_RESULT_WITH_AVAILABILITY_BIT` output for a single query: the
+/// value the driver wrote, and whether it's ready yet.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct AvailableU64 {
+    value: u64,
+    available: u64,
+}
+
+type DurationRange = [AvailableU64; 2];
+
+// `VkQueryPipelineStatisticFlagBits`, in the order the driver writes the
+// corresponding counters when more than one bit is set.
+const PIPELINE_STATISTIC_BITS: &[vk::QueryPipelineStatisticFlags] = &[
+    vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_VERTICES,
+    vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_PRIMITIVES,
+    vk::QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS,
+    vk::QueryPipelineStatisticFlags::GEOMETRY_SHADER_INVOCATIONS,
+    vk::QueryPipelineStatisticFlags::GEOMETRY_SHADER_PRIMITIVES,
+    vk::QueryPipelineStatisticFlags::CLIPPING_INVOCATIONS,
+    vk::QueryPipelineStatisticFlags::CLIPPING_PRIMITIVES,
+    vk::QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS,
+    vk::QueryPipelineStatisticFlags::TESSELLATION_CONTROL_SHADER_PATCHES,
+    vk::QueryPipelineStatisticFlags::TESSELLATION_EVALUATION_SHADER_INVOCATIONS,
+    vk::QueryPipelineStatisticFlags::COMPUTE_SHADER_INVOCATIONS,
+];
+
+fn pipeline_stats_from_raw(flags: vk::QueryPipelineStatisticFlags, raw: &[u64]) -> PipelineStats {
+    let mut stats = PipelineStats::default();
+    let mut it = raw.iter().copied();
+
+    for &bit in PIPELINE_STATISTIC_BITS {
+        if flags.contains(bit) {
+            let value = it.next();
+
+            match bit {
+                vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_VERTICES => {
+                    stats.input_assembly_vertices = value
+                }
+                vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_PRIMITIVES => {
+                    stats.input_assembly_primitives = value
+                }
+                vk::QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS => {
+                    stats.vertex_shader_invocations = value
+                }
+                vk::QueryPipelineStatisticFlags::GEOMETRY_SHADER_INVOCATIONS => {
+                    stats.geometry_shader_invocations = value
+                }
+                vk::QueryPipelineStatisticFlags::GEOMETRY_SHADER_PRIMITIVES => {
+                    stats.geometry_shader_primitives = value
+                }
+                vk::QueryPipelineStatisticFlags::CLIPPING_INVOCATIONS => {
+                    stats.clipping_invocations = value
+                }
+                vk::QueryPipelineStatisticFlags::CLIPPING_PRIMITIVES => {
+                    stats.clipping_primitives = value
+                }
+                vk::QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS => {
+                    stats.fragment_shader_invocations = value
+                }
+                vk::QueryPipelineStatisticFlags::TESSELLATION_CONTROL_SHADER_PATCHES => {
+                    stats.tessellation_control_shader_patches = value
+                }
+                vk::QueryPipelineStatisticFlags::TESSELLATION_EVALUATION_SHADER_INVOCATIONS => {
+                    stats.tessellation_evaluation_shader_invocations = value
+                }
+                vk::QueryPipelineStatisticFlags::COMPUTE_SHADER_INVOCATIONS => {
+                    stats.compute_shader_invocations = value
+                }
+                _ => unreachable!("not in PIPELINE_STATISTIC_BITS"),
+            }
+        }
+    }
+
+    stats
+}
+
+fn set_debug_utils_object_name(
+    debug_utils: &ash::ext::debug_utils::Device,
+    query_pool: vk::QueryPool,
+    name: &CStr,
+) {
+    use ash::vk::Handle;
+
+    let name_info = vk::DebugUtilsObjectNameInfoEXT::default()
+        .object_type(vk::ObjectType::QUERY_POOL)
+        .object_handle(query_pool.as_raw())
+        .object_name(name);
+
+    unsafe {
+        debug_utils
+            .set_debug_utils_object_name(&name_info)
+            .expect("set_debug_utils_object_name");
+    }
+}
+
+#[derive(Clone, Copy)]
 pub struct VulkanActiveScope {
+    scope_id: ScopeId,
     query_id: u32,
+    stats_query_id: Option<u32>,
 }
 
 impl<Buffer: VulkanBuffer> VulkanProfilerFrame<Buffer> {
-    pub fn new(device: &ash::Device, mut backend: impl VulkanBackend<Buffer = Buffer>) -> Self {
+    fn new(device: &ash::Device, backend: &mut impl VulkanBackend<Buffer = Buffer>) -> Self {
         let buffer = backend
             .create_query_result_buffer(MAX_QUERY_COUNT * std::mem::size_of::<DurationRange>());
 
@@ -40,24 +336,74 @@ impl<Buffer: VulkanBuffer> VulkanProfilerFrame<Buffer> {
             .query_type(vk::QueryType::TIMESTAMP)
             .query_count(MAX_QUERY_COUNT as u32 * 2);
 
+        let debug_utils = backend.debug_utils();
+        let query_pool = unsafe { device.create_query_pool(&pool_info, None) }
+            .expect("create_query_pool");
+
+        if let Some(debug_utils) = &debug_utils {
+            set_debug_utils_object_name(debug_utils, query_pool, c"gpu_profiler timestamps");
+        }
+
+        let stats_flags = backend.pipeline_statistics_flags();
+
+        let stats = stats_flags.map(|flags| {
+            let values_per_query = PIPELINE_STATISTIC_BITS
+                .iter()
+                .filter(|&&bit| flags.contains(bit))
+                .count();
+
+            // +1 per query for the availability word written by
+            // `VK_QUERY_RESULT_WITH_AVAILABILITY_BIT` in `copy_results`.
+            let buffer = backend.create_query_result_buffer(
+                MAX_QUERY_COUNT * (values_per_query + 1) * std::mem::size_of::<u64>(),
+            );
+
+            let pool_info = vk::QueryPoolCreateInfo::default()
+                .query_type(vk::QueryType::PIPELINE_STATISTICS)
+                .pipeline_statistics(flags)
+                .query_count(MAX_QUERY_COUNT as u32);
+
+            let query_pool = unsafe { device.create_query_pool(&pool_info, None) }
+                .expect("create_query_pool");
+
+            if let Some(debug_utils) = &debug_utils {
+                set_debug_utils_object_name(debug_utils, query_pool, c"gpu_profiler pipeline stats");
+            }
+
+            StatsQueryPool {
+                buffer,
+                query_pool,
+                next_query_idx: Default::default(),
+                query_scope_ids: (0..MAX_QUERY_COUNT)
+                    .map(|_| AtomicU64::new(ScopeId::invalid().as_u64()))
+                    .collect::<Vec<AtomicU64>>()
+                    .into(),
+                flags,
+                values_per_query,
+                active_depth: Default::default(),
+            }
+        });
+
         Self {
             buffer,
-            query_pool: unsafe { device.create_query_pool(&pool_info, None) }
-                .expect("create_query_pool"),
+            query_pool,
             next_query_idx: Default::default(),
             query_scope_ids: (1..MAX_QUERY_COUNT)
                 .map(|_| AtomicU64::new(ScopeId::invalid().as_u64()))
                 .collect::<Vec<AtomicU64>>()
                 .into(),
             timestamp_period: backend.timestamp_period(),
+            stats,
+            debug_utils,
         }
     }
 
-    pub fn begin_scope(
+    fn begin_scope(
         &self,
         device: &ash::Device,
         cb: ash::vk::CommandBuffer,
         scope_id: ScopeId,
+        name: &str,
     ) -> VulkanActiveScope {
         let query_id = self
             .next_query_idx
@@ -66,6 +412,14 @@ impl<Buffer: VulkanBuffer> VulkanProfilerFrame<Buffer> {
         self.query_scope_ids[query_id as usize]
             .store(scope_id.as_u64(), std::sync::atomic::Ordering::Relaxed);
 
+        if let Some(debug_utils) = &self.debug_utils {
+            let label = ScopeLabel::new(name);
+            let label_info = vk::DebugUtilsLabelEXT::default().label_name(label.as_c_str());
+            unsafe {
+                debug_utils.cmd_begin_debug_utils_label(cb, &label_info);
+            }
+        }
+
         unsafe {
             device.cmd_write_timestamp(
                 cb,
@@ -75,10 +429,39 @@ impl<Buffer: VulkanBuffer> VulkanProfilerFrame<Buffer> {
             );
         }
 
-        VulkanActiveScope { query_id }
+        let stats_query_id = self.stats.as_ref().and_then(|stats| {
+            // Only the outermost stats-covered scope gets a region query:
+            // `PIPELINE_STATISTICS` queries can't overlap in the same
+            // command buffer, so a nested scope simply isn't measured.
+            let depth = stats
+                .active_depth
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            if depth != 0 {
+                return None;
+            }
+
+            let stats_query_id = stats
+                .next_query_idx
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+            stats.query_scope_ids[stats_query_id as usize]
+                .store(scope_id.as_u64(), std::sync::atomic::Ordering::Relaxed);
+
+            unsafe {
+                device.cmd_begin_query(cb, stats.query_pool, stats_query_id, vk::QueryControlFlags::empty());
+            }
+
+            Some(stats_query_id)
+        });
+
+        VulkanActiveScope {
+            scope_id,
+            query_id,
+            stats_query_id,
+        }
     }
 
-    pub fn end_scope(
+    fn end_scope(
         &self,
         device: &ash::Device,
         cb: ash::vk::CommandBuffer,
@@ -92,22 +475,57 @@ impl<Buffer: VulkanBuffer> VulkanProfilerFrame<Buffer> {
                 active_scope.query_id * 2 + 1,
             );
         }
-    }
 
-    /// Call this before recording any profiling scopes in the frame
-    pub fn begin_frame(&self, device: &ash::Device, cmd: vk::CommandBuffer) {
-        self.report_durations();
+        if let Some(stats) = &self.stats {
+            // Balances the `fetch_add` in `begin_scope`, whether or not this
+            // particular scope was the one that got a region query.
+            stats
+                .active_depth
+                .fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
+
+            if let Some(stats_query_id) = active_scope.stats_query_id {
+                unsafe {
+                    device.cmd_end_query(cb, stats.query_pool, stats_query_id);
+                }
+            }
+        }
 
+        if let Some(debug_utils) = &self.debug_utils {
+            unsafe {
+                debug_utils.cmd_end_debug_utils_label(cb);
+            }
+        }
+    }
+
+    /// Resets the query pools for reuse. Must be called (with a command
+    /// buffer that hasn't started any queries yet) before recording any
+    /// scopes into a frame freshly taken out of the pool.
+    fn reset(&self, device: &ash::Device, cmd: vk::CommandBuffer) {
         unsafe {
             device.cmd_reset_query_pool(cmd, self.query_pool, 0, MAX_QUERY_COUNT as u32 * 2);
+
+            if let Some(stats) = &self.stats {
+                device.cmd_reset_query_pool(cmd, stats.query_pool, 0, MAX_QUERY_COUNT as u32);
+            }
         }
 
         self.next_query_idx
             .store(0, std::sync::atomic::Ordering::Relaxed);
+
+        if let Some(stats) = &self.stats {
+            stats
+                .next_query_idx
+                .store(0, std::sync::atomic::Ordering::Relaxed);
+            stats
+                .active_depth
+                .store(0, std::sync::atomic::Ordering::Relaxed);
+        }
     }
 
-    /// Call this after recording all profiling scopes in the frame
-    pub fn end_frame(&self, device: &ash::Device, cmd: vk::CommandBuffer) {
+    /// Copies this frame's query results into its mapped buffers without
+    /// waiting for them to become available; readiness is checked later, on
+    /// the host, via `VK_QUERY_RESULT_WITH_AVAILABILITY_BIT`.
+    fn copy_results(&self, device: &ash::Device, cmd: vk::CommandBuffer) {
         let valid_query_count = self
             .next_query_idx
             .load(std::sync::atomic::Ordering::Relaxed);
@@ -120,28 +538,51 @@ impl<Buffer: VulkanBuffer> VulkanProfilerFrame<Buffer> {
                 valid_query_count * 2,
                 self.buffer.raw(),
                 0,
-                8,
-                vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WAIT,
+                std::mem::size_of::<AvailableU64>() as u64,
+                vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WITH_AVAILABILITY,
             );
         }
-    }
 
-    fn report_durations(&self) {
-        let previous_results = self.retrieve_previous_results();
-        let ns_per_tick = self.timestamp_period as f64;
+        if let Some(stats) = &self.stats {
+            let valid_stats_query_count = stats
+                .next_query_idx
+                .load(std::sync::atomic::Ordering::Relaxed);
+
+            unsafe {
+                device.cmd_copy_query_pool_results(
+                    cmd,
+                    stats.query_pool,
+                    0,
+                    valid_stats_query_count,
+                    stats.buffer.raw(),
+                    0,
+                    ((stats.values_per_query + 1) * std::mem::size_of::<u64>()) as u64,
+                    vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WITH_AVAILABILITY,
+                );
+            }
+        }
+    }
 
-        crate::profiler().report_durations(previous_results.into_iter().map(
-            |(scope_id, duration_range)| {
-                let duration_ticks = duration_range[1].saturating_sub(duration_range[0]);
-                let duration =
-                    NanoSecond::from_raw_ns((duration_ticks as f64 * ns_per_tick) as u64);
+    /// Returns this frame's durations, and pipeline stats if enabled, once
+    /// every query in the frame has become available. Returns `None` (and
+    /// does not consume anything) if the driver hasn't finished writing the
+    /// results yet.
+    fn try_read_results(
+        &self,
+    ) -> Option<(
+        Vec<(ScopeId, NanoSecond, NanoSecond)>,
+        Option<Vec<(ScopeId, PipelineStats)>>,
+    )> {
+        let durations = self.try_read_durations()?;
+        let stats = match &self.stats {
+            Some(stats) => Some(Self::try_read_stats(stats)?),
+            None => None,
+        };
 
-                (scope_id, duration)
-            },
-        ));
+        Some((durations, stats))
     }
 
-    fn retrieve_previous_results(&self) -> Vec<(ScopeId, DurationRange)> {
+    fn try_read_durations(&self) -> Option<Vec<(ScopeId, NanoSecond, NanoSecond)>> {
         let valid_query_count = self
             .next_query_idx
             .load(std::sync::atomic::Ordering::Relaxed) as usize;
@@ -158,15 +599,88 @@ impl<Buffer: VulkanBuffer> VulkanProfilerFrame<Buffer> {
             )
         };
 
+        if !durations
+            .iter()
+            .all(|range| range.iter().all(|q| q.available != 0))
+        {
+            return None;
+        }
+
         let scopes = self.query_scope_ids[0..valid_query_count]
             .iter()
             .map(|val| ScopeId::from_u64(val.load(std::sync::atomic::Ordering::Relaxed)));
 
-        let mut result: Vec<(ScopeId, [u64; 2])> =
-            scopes.zip(durations.into_iter().copied()).collect();
-
+        let mut result: Vec<(ScopeId, DurationRange)> =
+            scopes.zip(durations.iter().copied()).collect();
         result.sort_unstable_by_key(|(scope, _)| *scope);
 
-        result
+        let ns_per_tick = self.timestamp_period as f64;
+
+        // Scopes are timestamped on an absolute GPU clock; anchor the frame
+        // at its earliest scope so nested scopes get real, relative start
+        // offsets instead of durations summed end-to-end.
+        let origin_ticks = result
+            .iter()
+            .map(|(_, duration_range)| duration_range[0].value)
+            .min()
+            .unwrap_or(0);
+
+        Some(
+            result
+                .into_iter()
+                .map(|(scope_id, duration_range)| {
+                    let start_ticks = duration_range[0].value.saturating_sub(origin_ticks);
+                    let duration_ticks =
+                        duration_range[1].value.saturating_sub(duration_range[0].value);
+
+                    let start =
+                        NanoSecond::from_raw_ns((start_ticks as f64 * ns_per_tick) as u64);
+                    let duration =
+                        NanoSecond::from_raw_ns((duration_ticks as f64 * ns_per_tick) as u64);
+
+                    (scope_id, start, duration)
+                })
+                .collect(),
+        )
+    }
+
+    fn try_read_stats(stats: &StatsQueryPool<Buffer>) -> Option<Vec<(ScopeId, PipelineStats)>> {
+        let valid_query_count = stats
+            .next_query_idx
+            .load(std::sync::atomic::Ordering::Relaxed) as usize;
+        let stride = stats.values_per_query + 1;
+
+        let mapped_slice = stats.buffer.mapped_slice();
+
+        assert_eq!(mapped_slice.len() % (stride * std::mem::size_of::<u64>()), 0);
+        assert!(mapped_slice.len() / std::mem::size_of::<u64>() >= valid_query_count * stride);
+
+        let raw_values = unsafe {
+            std::slice::from_raw_parts(
+                mapped_slice.as_ptr() as *const u64,
+                valid_query_count * stride,
+            )
+        };
+
+        if !raw_values
+            .chunks_exact(stride)
+            .all(|query| *query.last().unwrap() != 0)
+        {
+            return None;
+        }
+
+        let results = stats.query_scope_ids[0..valid_query_count]
+            .iter()
+            .map(|val| ScopeId::from_u64(val.load(std::sync::atomic::Ordering::Relaxed)))
+            .zip(raw_values.chunks_exact(stride))
+            .map(|(scope_id, query)| {
+                (
+                    scope_id,
+                    pipeline_stats_from_raw(stats.flags, &query[..stats.values_per_query]),
+                )
+            })
+            .collect();
+
+        Some(results)
     }
 }
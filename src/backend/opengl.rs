@@ -20,6 +20,8 @@ mod consts {
     pub const QUERY_RESULT_AVAILABLE: GLenum = 0x8867;
     pub const QUERY_RESULT: GLenum = 0x8866;
     pub const TIME_ELAPSED: GLenum = 0x88BF;
+    pub const TIMESTAMP: GLenum = 0x8E28;
+    pub const TIMESTAMP_EXT: GLenum = 0x8E28;
 }
 
 use consts::*;
@@ -33,30 +35,76 @@ pub trait GlBackend {
     fn GenQueries(&mut self, n: GLsizei, ids: *mut GLuint);
     fn BeginQuery(&mut self, target: GLenum, id: GLuint);
     fn EndQuery(&mut self, target: GLenum);
+
+    /// Writes the current GPU time into a query object. Requires
+    /// `ARB_timer_query` (core since GL 3.3) or `EXT_timer_query`.
+    fn QueryCounter(&mut self, id: GLuint, target: GLenum);
+
+    /// Reads a 64-bit integer driver parameter, e.g. `GL_TIMESTAMP` for the
+    /// GPU's current absolute time.
+    fn GetInteger64v(&mut self, pname: GLenum, params: *mut GLuint64);
+
+    /// Whether `QueryCounter(_, GL_TIMESTAMP)` is supported. `GL_TIME_ELAPSED`
+    /// is used as a fallback when it isn't, but `TIME_ELAPSED` queries can't
+    /// nest, so scopes are reported flat in that case. Defaults to `true`;
+    /// override to `false` on drivers lacking `ARB_timer_query`.
+    fn supports_timestamp_queries(&self) -> bool {
+        true
+    }
 }
 
 const MAX_QUERY_COUNT: usize = 1024;
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TimingMode {
+    /// One query object per scope, covering it with `BeginQuery`/`EndQuery`.
+    /// Can't nest: the GL spec forbids more than one `TIME_ELAPSED` query
+    /// being active at a time.
+    Elapsed,
+    /// Two query objects per scope, each stamped with the absolute GPU clock.
+    /// Scopes get real start offsets, so they can nest.
+    Timestamp,
+}
+
 struct GlProfilerFrame {
+    timing_mode: TimingMode,
     query_handles: Vec<GLuint>,
     next_query_idx: usize,
     query_scope_ids: Vec<ScopeId>,
     results_buffer: Vec<u64>,
+    // Reused scratch space for the (start, duration) pairs computed from
+    // `results_buffer`; avoids reallocating every time a frame is read back.
+    spans: Vec<(NanoSecond, NanoSecond)>,
 }
 
 pub struct GlActiveScope {
-    query_handle: GLuint,
+    scope_id: ScopeId,
+    query_id: usize,
 }
 
 impl GlProfilerFrame {
     pub fn new(backend: &mut impl GlBackend) -> Self {
-        let mut queries = vec![0; MAX_QUERY_COUNT];
-        backend.GenQueries(MAX_QUERY_COUNT as _, queries.as_mut_ptr());
+        let timing_mode = if backend.supports_timestamp_queries() {
+            TimingMode::Timestamp
+        } else {
+            TimingMode::Elapsed
+        };
+
+        let query_count = match timing_mode {
+            TimingMode::Elapsed => MAX_QUERY_COUNT,
+            TimingMode::Timestamp => MAX_QUERY_COUNT * 2,
+        };
+
+        let mut queries = vec![0; query_count];
+        backend.GenQueries(query_count as _, queries.as_mut_ptr());
+
         Self {
+            timing_mode,
             query_handles: queries,
             next_query_idx: 0,
             query_scope_ids: vec![ScopeId::invalid(); MAX_QUERY_COUNT],
-            results_buffer: vec![0; MAX_QUERY_COUNT],
+            results_buffer: vec![0; query_count],
+            spans: Vec::with_capacity(MAX_QUERY_COUNT),
         }
     }
 
@@ -68,24 +116,46 @@ impl GlProfilerFrame {
         let query_id = self.next_query_idx;
         self.next_query_idx += 1;
 
-        self.query_scope_ids[query_id as usize] = scope_id;
+        self.query_scope_ids[query_id] = scope_id;
 
-        let query_handle = self.query_handles[query_id];
-
-        backend.BeginQuery(TIME_ELAPSED, query_handle);
+        match self.timing_mode {
+            TimingMode::Elapsed => {
+                backend.BeginQuery(TIME_ELAPSED, self.query_handles[query_id]);
+            }
+            TimingMode::Timestamp => {
+                backend.QueryCounter(self.query_handles[query_id * 2], TIMESTAMP);
+            }
+        }
 
-        GlActiveScope { query_handle }
+        GlActiveScope { scope_id, query_id }
     }
 
     pub fn end_scope(&self, backend: &mut impl GlBackend, active_scope: GlActiveScope) {
-        assert!(active_scope.query_handle == self.query_handles[self.next_query_idx - 1]);
-        backend.EndQuery(TIME_ELAPSED);
+        // No LIFO check here: each scope owns an independent begin/end query
+        // pair, so a child scope's end_scope legitimately runs before its
+        // still-open parent's in `TimingMode::Timestamp`.
+        match self.timing_mode {
+            TimingMode::Elapsed => backend.EndQuery(TIME_ELAPSED),
+            TimingMode::Timestamp => {
+                backend.QueryCounter(self.query_handles[active_scope.query_id * 2 + 1], TIMESTAMP);
+            }
+        }
     }
 
-    fn read_results(&mut self, backend: &mut impl GlBackend) -> Option<(&[ScopeId], &[u64])> {
+    /// Returns the frame's scope ids and, index for index, their
+    /// `(start_offset_ns, duration_ns)` spans, or `None` if any query result
+    /// isn't available yet.
+    fn read_results(
+        &mut self,
+        backend: &mut impl GlBackend,
+    ) -> Option<(&[ScopeId], &[(NanoSecond, NanoSecond)])> {
         let result_count = self.next_query_idx;
+        let query_count = match self.timing_mode {
+            TimingMode::Elapsed => result_count,
+            TimingMode::Timestamp => result_count * 2,
+        };
 
-        let results_available = self.query_handles[0..result_count]
+        let results_available = self.query_handles[0..query_count]
             .iter()
             .all(|&query_handle| {
                 let mut available: i32 = 0;
@@ -93,23 +163,45 @@ impl GlProfilerFrame {
                 available != 0
             });
 
-        if results_available {
-            for (&handle, result_nanos) in self
-                .query_handles
-                .iter()
-                .zip(self.results_buffer.iter_mut())
-                .take(result_count)
-            {
-                backend.GetQueryObjectui64v(handle, QUERY_RESULT, result_nanos);
-            }
+        if !results_available {
+            return None;
+        }
 
-            Some((
-                &self.query_scope_ids[0..result_count],
-                &self.results_buffer[0..result_count],
-            ))
-        } else {
-            None
+        for (&handle, result) in self
+            .query_handles
+            .iter()
+            .zip(self.results_buffer.iter_mut())
+            .take(query_count)
+        {
+            backend.GetQueryObjectui64v(handle, QUERY_RESULT, result);
         }
+
+        self.spans.clear();
+        match self.timing_mode {
+            TimingMode::Elapsed => {
+                let mut start_ns = 0;
+                for &duration in &self.results_buffer[0..result_count] {
+                    self.spans
+                        .push((NanoSecond::from_raw_ns(start_ns), NanoSecond::from_raw_ns(duration)));
+                    start_ns += duration;
+                }
+            }
+            TimingMode::Timestamp => {
+                let timestamps = &self.results_buffer[0..query_count];
+                let origin_ns = timestamps.iter().step_by(2).copied().min().unwrap_or(0);
+
+                for pair in timestamps.chunks_exact(2) {
+                    let start_ns = pair[0].saturating_sub(origin_ns);
+                    let duration_ns = pair[1].saturating_sub(pair[0]);
+                    self.spans.push((
+                        NanoSecond::from_raw_ns(start_ns),
+                        NanoSecond::from_raw_ns(duration_ns),
+                    ));
+                }
+            }
+        }
+
+        Some((&self.query_scope_ids[0..result_count], &self.spans))
     }
 
     fn reset(&mut self) {
@@ -171,20 +263,24 @@ impl GlProfiler {
     }
 
     pub fn end_scope(&mut self, backend: &mut impl GlBackend, active_scope: GlActiveScope) {
+        let scope_id = active_scope.scope_id;
+
         self.current_frame
             .as_mut()
             .expect("end_scope called before begin_frame")
-            .end_scope(backend, active_scope)
+            .end_scope(backend, active_scope);
+
+        crate::profiler().end_scope(scope_id);
     }
 
     fn try_get_results(&mut self, backend: &mut impl GlBackend) {
         while let Some(frame) = self.waiting_frames.front_mut() {
-            if let Some((scopes, durations)) = frame.read_results(backend) {
+            if let Some((scopes, spans)) = frame.read_results(backend) {
                 crate::profiler().report_durations(
                     scopes
                         .iter()
-                        .zip(durations.iter())
-                        .map(|(&scope, &duration)| (scope, NanoSecond::from_raw_ns(duration))),
+                        .zip(spans.iter())
+                        .map(|(&scope, &(start, duration))| (scope, start, duration)),
                 );
 
                 let mut frame = self.waiting_frames.pop_front().unwrap();
@@ -48,6 +48,8 @@ impl NanoSecond {
 #[derive(Clone)]
 struct Scope {
     name: String,
+    parent: Option<u32>,
+    depth: u32,
 }
 
 #[derive(Default, Clone, Copy)]
@@ -67,6 +69,8 @@ enum FrameState {
 struct Frame {
     state: FrameState,
     scopes: Vec<Scope>,
+    // Indices into `scopes` of the currently open scopes, innermost last.
+    scope_stack: Vec<u32>,
 }
 
 pub struct GpuProfiler {
@@ -76,10 +80,35 @@ pub struct GpuProfiler {
     last_report: Option<TimedFrame>,
 }
 
+/// Pipeline statistics captured for a scope, when the backend enabled
+/// `pipelineStatisticsQuery` and requested the corresponding counters.
+/// Each field is `Some` only if the matching `VkQueryPipelineStatisticFlagBits`
+/// was requested; the raw values are the driver's un-normalized counters.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PipelineStats {
+    pub input_assembly_vertices: Option<u64>,
+    pub input_assembly_primitives: Option<u64>,
+    pub vertex_shader_invocations: Option<u64>,
+    pub geometry_shader_invocations: Option<u64>,
+    pub geometry_shader_primitives: Option<u64>,
+    pub clipping_invocations: Option<u64>,
+    pub clipping_primitives: Option<u64>,
+    pub fragment_shader_invocations: Option<u64>,
+    pub tessellation_control_shader_patches: Option<u64>,
+    pub tessellation_evaluation_shader_invocations: Option<u64>,
+    pub compute_shader_invocations: Option<u64>,
+}
+
 #[derive(Clone)]
 pub struct TimedScope {
     pub name: String,
+    /// Start of the scope, relative to the start of the earliest scope in the frame.
+    pub start: NanoSecond,
     pub duration: NanoSecond,
+    pub stats: Option<PipelineStats>,
+    /// Index into the owning `TimedFrame::scopes`, or `None` at the root.
+    pub parent: Option<u32>,
+    pub depth: u32,
 }
 
 #[derive(Default, Clone)]
@@ -109,10 +138,17 @@ impl GpuProfiler {
 
         frame.state = FrameState::Begin { index: frame_idx };
         frame.scopes.clear();
+        frame.scope_stack.clear();
     }
 
     pub fn end_frame(&mut self) {
         let frame = self.frame_mut();
+
+        assert!(
+            frame.scope_stack.is_empty(),
+            "end_frame called with open scopes; every create_scope needs a matching end_scope"
+        );
+
         frame.state = match frame.state {
             FrameState::Invalid | FrameState::Reported => {
                 panic!("end_frame called without begin_frame")
@@ -125,19 +161,49 @@ impl GpuProfiler {
     }
 
     pub fn create_scope(&mut self, name: impl Into<String>) -> ScopeId {
+        let frame_idx = self.frame_idx;
         let frame = self.frame_mut();
-        let next_scope_id = frame.scopes.len() as _;
+        let next_scope_id = frame.scopes.len() as u32;
+
+        let parent = frame.scope_stack.last().copied();
+        let depth = parent.map_or(0, |parent| frame.scopes[parent as usize].depth + 1);
 
-        frame.scopes.push(Scope { name: name.into() });
+        frame.scopes.push(Scope {
+            name: name.into(),
+            parent,
+            depth,
+        });
+        frame.scope_stack.push(next_scope_id);
 
         ScopeId {
-            frame: self.frame_idx as _,
+            frame: frame_idx,
             scope: next_scope_id,
         }
     }
 
-    pub fn report_durations(&mut self, mut durations: impl Iterator<Item = (ScopeId, NanoSecond)>) {
-        self.last_report = durations.next().map(|(scope_id, duration)| {
+    /// Bookkeeping counterpart to `create_scope`: pops the scope off the
+    /// per-frame stack so the next sibling (or the parent, once all its
+    /// children are closed) sees the correct nesting. This only tracks the
+    /// scope tree; ending the GPU-side query is the backend's job.
+    pub fn end_scope(&mut self, scope_id: ScopeId) {
+        let frame = self.frame_mut();
+
+        let popped = frame
+            .scope_stack
+            .pop()
+            .expect("end_scope called without a matching create_scope");
+
+        assert_eq!(
+            popped, scope_id.scope,
+            "end_scope called out of order; scopes must be closed in LIFO order"
+        );
+    }
+
+    pub fn report_durations(
+        &mut self,
+        mut durations: impl Iterator<Item = (ScopeId, NanoSecond, NanoSecond)>,
+    ) {
+        self.last_report = durations.next().map(|(scope_id, start, duration)| {
             // TODO: assert on the frame being in the valid range
             let first_scope_frame_idx = scope_id.frame;
             let frame_count = self.frames.len();
@@ -157,18 +223,24 @@ impl GpuProfiler {
                 }
             };
 
-            let timed_frame = std::iter::once(TimedScope {
-                name: std::mem::take(&mut frame.scopes[scope_id.scope as usize].name),
-                duration,
-            })
-            .chain(durations.map(|(scope_id, duration)| {
-                assert!(scope_id.frame == first_scope_frame_idx);
-
+            fn timed_scope(frame: &mut Frame, scope_id: ScopeId, start: NanoSecond, duration: NanoSecond) -> TimedScope {
+                let scope = &mut frame.scopes[scope_id.scope as usize];
                 TimedScope {
-                    name: std::mem::take(&mut frame.scopes[scope_id.scope as usize].name),
+                    name: std::mem::take(&mut scope.name),
+                    start,
                     duration,
+                    stats: None,
+                    parent: scope.parent,
+                    depth: scope.depth,
                 }
-            }));
+            }
+
+            let timed_frame = std::iter::once(timed_scope(frame, scope_id, start, duration)).chain(
+                durations.map(|(scope_id, start, duration)| {
+                    assert!(scope_id.frame == first_scope_frame_idx);
+                    timed_scope(frame, scope_id, start, duration)
+                }),
+            );
 
             let scopes = timed_frame.collect();
 
@@ -176,6 +248,19 @@ impl GpuProfiler {
         });
     }
 
+    /// Attaches pipeline statistics to the scopes of the most recently
+    /// reported frame. Must be called after `report_durations` for the same
+    /// frame; scopes are matched up by `ScopeId`, same as durations are.
+    pub fn report_pipeline_stats(&mut self, stats: impl Iterator<Item = (ScopeId, PipelineStats)>) {
+        if let Some(last_report) = self.last_report.as_mut() {
+            for (scope_id, stats) in stats {
+                if let Some(timed_scope) = last_report.scopes.get_mut(scope_id.scope as usize) {
+                    timed_scope.stats = Some(stats);
+                }
+            }
+        }
+    }
+
     fn frame_mut(&mut self) -> &mut Frame {
         let frame_count = self.frames.len();
         &mut self.frames[self.frame_idx as usize % frame_count]
@@ -193,20 +278,54 @@ impl GpuProfiler {
 }
 
 impl TimedFrame {
+    /// Children of each scope, indexed by parent scope index; root scopes
+    /// (no parent) are collected under `children[self.scopes.len()]`.
+    fn children_by_parent(&self) -> Vec<Vec<u32>> {
+        let mut children = vec![Vec::new(); self.scopes.len() + 1];
+        for (idx, scope) in self.scopes.iter().enumerate() {
+            let parent = scope.parent.map_or(self.scopes.len(), |p| p as usize);
+            children[parent].push(idx as u32);
+        }
+        children
+    }
+
+    fn emit_scope_to_puffin(
+        &self,
+        stream: &mut puffin::Stream,
+        children: &[Vec<u32>],
+        gpu_frame_start_ns: puffin::NanoSecond,
+        scope_idx: u32,
+    ) -> puffin::NanoSecond {
+        let scope = &self.scopes[scope_idx as usize];
+        let start_ns = gpu_frame_start_ns + scope.start.raw_ns() as puffin::NanoSecond;
+        let end_ns = start_ns + scope.duration.raw_ns() as puffin::NanoSecond;
+
+        let offset = stream.begin_scope(start_ns, &scope.name, "", "");
+        for &child_idx in &children[scope_idx as usize] {
+            self.emit_scope_to_puffin(stream, children, gpu_frame_start_ns, child_idx);
+        }
+        stream.end_scope(offset, end_ns);
+
+        end_ns
+    }
+
     pub fn send_to_puffin(&self, gpu_frame_start_ns: puffin::NanoSecond) {
         let mut stream = puffin::Stream::default();
-        let mut gpu_time_accum: puffin::NanoSecond = 0;
-        let mut puffin_scope_count = 0;
+        let children = self.children_by_parent();
+        let root_scopes = &children[self.scopes.len()];
+
+        let max_depth = self.scopes.iter().map(|s| s.depth).max();
+        let puffin_scope_count = self.scopes.len() + 1;
+
+        let mut frame_end_ns = gpu_frame_start_ns;
         let main_gpu_scope_offset = stream.begin_scope(gpu_frame_start_ns, "frame", "", "");
-        puffin_scope_count += 1;
-        puffin_scope_count += self.scopes.len();
-        for TimedScope { name, duration } in &self.scopes {
-            let ns = duration.raw_ns() as puffin::NanoSecond;
-            let offset = stream.begin_scope(gpu_frame_start_ns + gpu_time_accum, name, "", "");
-            gpu_time_accum += ns;
-            stream.end_scope(offset, gpu_frame_start_ns + gpu_time_accum);
+        for &root_idx in root_scopes {
+            let end_ns =
+                self.emit_scope_to_puffin(&mut stream, &children, gpu_frame_start_ns, root_idx);
+            frame_end_ns = frame_end_ns.max(end_ns);
         }
-        stream.end_scope(main_gpu_scope_offset, gpu_frame_start_ns + gpu_time_accum);
+        stream.end_scope(main_gpu_scope_offset, frame_end_ns);
+
         puffin::global_reporter(
             puffin::ThreadInfo {
                 start_time_ns: None,
@@ -215,10 +334,77 @@ impl TimedFrame {
             &puffin::StreamInfo {
                 num_scopes: puffin_scope_count,
                 stream,
-                depth: 1,
-                range_ns: (gpu_frame_start_ns, gpu_frame_start_ns + gpu_time_accum),
+                // `depth` is 0-based, so the tree has `d + 1` levels; +1 more
+                // for the synthetic "frame" scope wrapping the real tree.
+                depth: max_depth.map_or(1, |d| d + 2),
+                range_ns: (gpu_frame_start_ns, frame_end_ns),
             }
             .as_stream_into_ref(),
         );
     }
+
+    /// Serializes the captured scopes as a Chrome Trace Event JSON array
+    /// (`"ph":"X"` complete events), importable into `chrome://tracing` or
+    /// Perfetto. `pid`/`tid` are used to place the GPU timeline on its own
+    /// track; `process_name`/`thread_name` are shown as labels for it.
+    pub fn to_chrome_trace_json(&self, process_name: &str, thread_name: &str) -> String {
+        const PID: u32 = 1;
+        const TID: u32 = 1;
+
+        let mut json = String::new();
+        json.push('[');
+
+        json.push_str(&format!(
+            concat!(
+                r#"{{"ph":"M","name":"process_name","pid":{pid},"#,
+                r#""args":{{"name":{process_name}}}}},"#,
+                r#"{{"ph":"M","name":"thread_name","pid":{pid},"tid":{tid},"#,
+                r#""args":{{"name":{thread_name}}}}}"#,
+            ),
+            pid = PID,
+            tid = TID,
+            process_name = json_escape(process_name),
+            thread_name = json_escape(thread_name),
+        ));
+
+        for scope in &self.scopes {
+            json.push(',');
+            json.push_str(&format!(
+                r#"{{"ph":"X","name":{name},"ts":{ts},"dur":{dur},"pid":{pid},"tid":{tid}}}"#,
+                name = json_escape(&scope.name),
+                ts = ns_to_us(scope.start),
+                dur = ns_to_us(scope.duration),
+                pid = PID,
+                tid = TID,
+            ));
+        }
+
+        json.push(']');
+        json
+    }
+}
+
+fn ns_to_us(ns: NanoSecond) -> f64 {
+    ns.raw_ns() as f64 / 1_000.0
+}
+
+/// Minimal JSON string escaping; scope names are user-supplied but expected
+/// to be plain ASCII labels, so this only needs to handle the characters
+/// that would otherwise break the JSON syntax.
+fn json_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
 }